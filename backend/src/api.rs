@@ -0,0 +1,91 @@
+// Copyright 2020 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Public HTTP API exposing the Merkle proofs defined on `Schema`/`SchemaImpl`,
+//! so a trainer or auditor running without full node state can fetch a
+//! compact proof of the current model or a round's contributions.
+//!
+//! NOTE: this crate snapshot has no service/lib module wiring a
+//! `wire_api` implementation. Until the service's `wire_api` calls
+//! `ModelApi::wire` from its `ServiceApiBuilder`, these endpoints are defined
+//! but not actually reachable — this must happen before merge into a tree
+//! that has that module, or the proofs stay unshipped.
+
+use exonum::{
+    crypto::Hash,
+    merkledb::{ListProof, MapProof},
+    runtime::CallerAddress as Address,
+};
+use exonum_rust_runtime::api::{self, ServiceApiBuilder, ServiceApiState};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{model::Model, schema::SchemaImpl};
+
+/// Query for `ModelApi::model_proof`; omit `version_hash` to prove the
+/// latest model version instead of a specific one.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ModelProofQuery {
+    pub version_hash: Option<Address>,
+}
+
+/// Query for `ModelApi::contribution_proof`; omit `index` to prove the
+/// whole round's contribution history instead of a single entry.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContributionProofQuery {
+    pub version: u32,
+    pub index: Option<u64>,
+}
+
+/// Read-only API over the Merkle proofs defined on `Schema`/`SchemaImpl`.
+#[derive(Debug, Clone, Copy)]
+pub struct ModelApi;
+
+impl ModelApi {
+    /// Proves the requested model version, or the latest one if
+    /// `version_hash` is omitted.
+    pub fn model_proof(
+        state: &ServiceApiState,
+        query: ModelProofQuery,
+    ) -> api::Result<MapProof<Address, Model>> {
+        let schema = SchemaImpl::new(state.service_data());
+        match query.version_hash {
+            Some(version_hash) => Ok(schema.public.model_proof(version_hash)),
+            None => schema
+                .public
+                .latest_model_proof()
+                .ok_or_else(|| api::Error::not_found().title("No model has been created yet")),
+        }
+    }
+
+    /// Proves a single contribution (`index` given) or the full round's
+    /// contribution history (`index` omitted) for `version`.
+    pub fn contribution_proof(
+        state: &ServiceApiState,
+        query: ContributionProofQuery,
+    ) -> api::Result<ListProof<Hash>> {
+        let schema = SchemaImpl::new(state.service_data());
+        Ok(match query.index {
+            Some(index) => schema.contribution_proof(query.version, index),
+            None => schema.model_history_proof(query.version),
+        })
+    }
+
+    /// Registers these endpoints on the service's public API scope.
+    pub fn wire(builder: &mut ServiceApiBuilder) {
+        builder
+            .public_scope()
+            .endpoint("v1/model/proof", Self::model_proof)
+            .endpoint("v1/model/contribution-proof", Self::contribution_proof);
+    }
+}
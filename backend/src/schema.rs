@@ -14,14 +14,15 @@
 
 //! Cryptocurrency database schema.
 use exonum::{
-    crypto::{Hash, PublicKey},
+    crypto::{hash, Hash, PublicKey},
     merkledb::{
         access::{Access, FromAccess, RawAccessMut},
-        Entry, Group, MapIndex, ProofListIndex, RawProofMapIndex,
+        Entry, Group, ListProof, MapIndex, MapProof, ProofListIndex, RawProofMapIndex,
     },
     runtime::CallerAddress as Address,
 };
 use exonum_derive::{FromAccess, RequireArtifact};
+use std::convert::TryInto;
 
 // modified
 use crate::{model::Model, INIT_WEIGHT, LAMBDA, MODEL_SIZE, MAJORITY_RATIO};
@@ -29,6 +30,62 @@ use crate::{model::Model, INIT_WEIGHT, LAMBDA, MODEL_SIZE, MAJORITY_RATIO};
 
 const DEBUG: bool = true;
 
+/// Robust-aggregation strategy used by [`SchemaImpl::update_weights`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregationMode {
+    /// Reputation-weighted FedAvg over every pending update from a trainer
+    /// that is not slashed, with staleness-dampened weights renormalized to sum to 1.
+    FedAvg,
+    /// Multi-Krum: average the `n - f` updates with the smallest sum of
+    /// squared distances to their closest neighbors.
+    MultiKrum,
+    /// Coordinate-wise trimmed mean: per coordinate, drop the highest/lowest
+    /// `TRIMMED_BETA * n` values before averaging.
+    TrimmedMean,
+}
+
+/// Aggregation mode selected for this deployment.
+pub const AGGREGATION_MODE: AggregationMode = AggregationMode::MultiKrum;
+/// Assumed upper bound on the number of Byzantine (malicious) trainers per round.
+pub const BYZANTINE_F: usize = 1;
+/// Fraction of values trimmed from each end of a coordinate in `TrimmedMean` mode.
+pub const TRIMMED_BETA: f32 = 0.2;
+/// Maximum number of model versions a pending update may lag behind
+/// `latest_version_addr` before it is rejected outright as stale.
+pub const MAX_STALENESS: u32 = 3;
+/// Cosine similarity (to the round's aggregated delta) below which a
+/// trainer's contribution counts as a low-quality strike.
+pub const LOW_QUALITY_THRESHOLD: f32 = 0.0;
+/// Number of consecutive low-quality rounds before a trainer is slashed.
+pub const SLASH_STREAK_LIMIT: u32 = 3;
+/// Score floor a slashed trainer is pinned to; such trainers are skipped
+/// entirely in majority-ratio checks and aggregation.
+pub const SCORE_FLOOR: f32 = 1e-6;
+
+/// Update envelope tag: dense `MODEL_SIZE` f32 vector (the original layout).
+const TAG_DENSE: u8 = 0;
+/// Update envelope tag: top-k sparse `(u32 index, f32 value)` pairs.
+const TAG_SPARSE_TOPK: u8 = 1;
+/// Update envelope tag: int8 values quantized with a per-tensor f32 scale.
+const TAG_QUANTIZED_INT8: u8 = 2;
+
+/// Why a typed update envelope from an untrusted trainer was rejected.
+/// Submitted bytes are attacker-controlled, so `decode_update` must return
+/// this instead of panicking on malformed input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The payload had no leading tag byte.
+    Empty,
+    /// The leading tag byte didn't match a known envelope.
+    UnknownTag(u8),
+    /// The payload length didn't match what the tag requires.
+    WrongLength { expected: usize, actual: usize },
+    /// A sparse index pointed past `MODEL_SIZE`.
+    IndexOutOfBounds(u32),
+    /// A decoded coordinate was NaN or infinite.
+    NonFiniteValue,
+}
+
 /// Database schema for the cryptocurrency.
 ///
 /// Note that the schema is crate-private, but it has a public part.
@@ -44,6 +101,12 @@ pub(crate) struct SchemaImpl<T: Access> {
     pub trainers_scores: MapIndex<T::Base, Address, String>,
     /// Pending transactions of the current round
     pub pending_transactions: MapIndex<T::Base, Address, Vec<u8>>,
+    /// Model version each pending update was trained against, keyed by trainer.
+    /// Used to compute staleness when aggregating.
+    pub pending_versions: MapIndex<T::Base, Address, u32>,
+    /// Number of consecutive low-quality rounds a trainer has produced,
+    /// used to decide when to slash its score.
+    pub trainer_strikes: MapIndex<T::Base, Address, u32>,
 }
 
 /// Public part of the cryptocurrency schema.
@@ -57,10 +120,38 @@ pub struct Schema<T: Access> {
     pub latest_version_addr: Entry<T::Base, Address>,
 }
 
+impl<T: Access> Schema<T> {
+    /// Returns a Merkle proof of `version_hash`'s presence (or absence) in
+    /// `models`, letting a light client verify a model without trusting a full node.
+    pub fn model_proof(&self, version_hash: Address) -> MapProof<Address, Model> {
+        self.models.get_proof(version_hash)
+    }
+
+    /// Returns a Merkle proof for the model at `latest_version_addr`, i.e.
+    /// proof of which global model is currently canonical.
+    pub fn latest_model_proof(&self) -> Option<MapProof<Address, Model>> {
+        self.latest_version_addr.get().map(|addr| self.model_proof(addr))
+    }
+}
+
 impl<T: Access> SchemaImpl<T> {
     pub fn new(access: T) -> Self {
         Self::from_root(access).unwrap()
     }
+
+    /// Returns a Merkle proof for the entry at `index` in a given `version`'s
+    /// contribution history (hashes of the dense-encoded updates recorded by
+    /// `update_weights`), letting a trainer prove its update was aggregated
+    /// into that version without re-downloading the whole round.
+    pub fn contribution_proof(&self, version: u32, index: u64) -> ListProof<Hash> {
+        self.model_history.get(version).get_proof(index)
+    }
+
+    /// Returns a Merkle proof over the full contribution history recorded
+    /// for `version`, for an auditor checking the whole round at once.
+    pub fn model_history_proof(&self, version: u32) -> ListProof<Hash> {
+        self.model_history.get(version).get_range_proof(..)
+    }
 }
 
 impl<T> SchemaImpl<T>
@@ -120,36 +211,210 @@ where
             (&latest_model).weights.clone(),
         );
 
-        /// Aggregating all pending transactions
+        // Buffer every pending update first: robust aggregation needs the whole
+        // batch at once (to compute pairwise distances / order statistics),
+        // it can't be folded in one at a time like plain FedAvg.
+        let mut updates: Vec<(Address, Vec<f32>, f32)> = Vec::new();
         for pending_transaction in self.pending_transactions.iter(){
             let trainer_addr = pending_transaction.0;
-            let updates = SchemaUtils::byte_slice_to_float_vec(&pending_transaction.1);
+            // check_pending already validated this payload before storing it,
+            // but never trust stored bytes to decode cleanly a second time:
+            // skip rather than panic if they somehow don't.
+            let update = match SchemaUtils::decode_update(&pending_transaction.1) {
+                Ok(update) => update,
+                Err(_) => continue,
+            };
             let trainer_score = self.trainers_scores.get(&trainer_addr).unwrap();
             let tw_f32 = trainer_score.parse::<f32>().unwrap();
-            new_model.aggregate(&updates, tw_f32);
+            if tw_f32 <= SCORE_FLOOR {
+                // Slashed trainer: excluded from aggregation entirely.
+                continue;
+            }
+
+            // Dampen the weight of stale updates: a trainer who trained against
+            // an older model version contributes a less trustworthy gradient.
+            let submitted_version = self.pending_versions.get(&trainer_addr).unwrap_or(latest_model.version);
+            let staleness = latest_model.version.saturating_sub(submitted_version);
+            let dampening = 1.0 / (1.0 + staleness as f32);
+
+            updates.push((trainer_addr, update, tw_f32 * dampening));
         }
         self.pending_transactions.clear();
-    
+        self.pending_versions.clear();
+
         let new_version = new_model.version;
+        let n = updates.len();
+        let enough_for_robust_estimate = n > 2 * BYZANTINE_F + 2;
+        // Record a hash in this round's model history for each update fed to
+        // the aggregator. In Multi-Krum mode only the selected updates are
+        // recorded, so a Merkle proof can't show an update rejected as an
+        // outlier; the trimmed-mean and FedAvg paths record every update.
+        let mut history = self.model_history.get(new_version);
+        match AGGREGATION_MODE {
+            AggregationMode::MultiKrum if enough_for_robust_estimate => {
+                println!("Aggregating {} updates via Multi-Krum (f={})", n, BYZANTINE_F);
+                for (update, weight) in SchemaUtils::multi_krum_select(&updates, BYZANTINE_F) {
+                    history.push(hash(&SchemaUtils::encode_dense(&update)));
+                    new_model.aggregate(&update, weight);
+                }
+            }
+            AggregationMode::TrimmedMean if enough_for_robust_estimate => {
+                println!("Aggregating {} updates via coordinate-wise trimmed mean (beta={})", n, TRIMMED_BETA);
+                for (_, update, _) in &updates {
+                    history.push(hash(&SchemaUtils::encode_dense(update)));
+                }
+                let trimmed = SchemaUtils::trimmed_mean(&updates, TRIMMED_BETA);
+                new_model.aggregate(&trimmed, 1.0);
+            }
+            _ => {
+                // Too few updates for a robust estimator to be meaningful, or
+                // plain FedAvg was selected: fall back to reputation-weighted
+                // averaging. Renormalize so the (staleness-dampened) weights
+                // sum to 1, matching the Multi-Krum path instead of shrinking
+                // the applied delta whenever any update was dampened.
+                let weight_sum: f32 = updates.iter().map(|(_, _, w)| *w).sum();
+                for (_, update, weight) in &updates {
+                    history.push(hash(&SchemaUtils::encode_dense(update)));
+                    let normalized_weight = if weight_sum > 0.0 { weight / weight_sum } else { *weight };
+                    new_model.aggregate(update, normalized_weight);
+                }
+            }
+        }
+
+        // Score each contributor by how well its update agreed with the delta
+        // that actually got applied, then decay/slash reputations accordingly.
+        let final_delta: Vec<f32> = new_model
+            .weights
+            .iter()
+            .zip(latest_model.weights.iter())
+            .map(|(new, old)| new - old)
+            .collect();
+
+        let mut updated_scores: Vec<(Address, f32)> = Vec::new();
+        for (trainer_addr, update, _weight) in &updates {
+            let quality = SchemaUtils::cosine_similarity(update, &final_delta);
+            let old_score = self.trainers_scores.get(trainer_addr).unwrap().parse::<f32>().unwrap();
+
+            let streak = if quality < LOW_QUALITY_THRESHOLD {
+                self.trainer_strikes.get(trainer_addr).unwrap_or(0) + 1
+            } else {
+                0
+            };
+            self.trainer_strikes.put(trainer_addr, streak);
+
+            let score = if streak >= SLASH_STREAK_LIMIT {
+                println!("Slashing {:?}: {} consecutive low-quality rounds", trainer_addr, streak);
+                SCORE_FLOOR
+            } else {
+                LAMBDA as f32 * old_score + (1.0 - LAMBDA as f32) * quality.max(0.0).min(1.0)
+            };
+            updated_scores.push((*trainer_addr, score));
+        }
+
+        // Renormalize so all scores (including non-contributors') sum to 1.
+        let mut all_scores: Vec<(Address, f32)> = self
+            .trainers_scores
+            .iter()
+            .map(|(addr, s)| (addr, s.parse::<f32>().unwrap()))
+            .collect();
+        for (addr, score) in &updated_scores {
+            if let Some(entry) = all_scores.iter_mut().find(|(a, _)| a == addr) {
+                entry.1 = *score;
+            }
+        }
+        // Slashed trainers must stay at the floor: dividing SCORE_FLOOR by a
+        // sub-1.0 total would otherwise push it back above the floor, undoing
+        // the "skipped entirely" guarantee on the very next round.
+        let total: f32 = all_scores
+            .iter()
+            .filter(|(_, s)| *s > SCORE_FLOOR)
+            .map(|(_, s)| s)
+            .sum();
+        for (addr, score) in all_scores {
+            let normalized = if score <= SCORE_FLOOR {
+                SCORE_FLOOR
+            } else if total > 0.0 {
+                score / total
+            } else {
+                score
+            };
+            self.trainers_scores.put(&addr, normalized.to_string());
+        }
+
         let new_version_hash = Address::from_key(SchemaUtils::pubkey_from_version(new_version));
         println!("Created New Model: {:?}", new_model);
         self.public.models.put(&new_version_hash, new_model);
         self.public.latest_version_addr.set(new_version_hash);
     }
 
-    pub fn check_pending(&mut self, trainer_addr: &Address, updates: &Vec<f32>) -> bool{
+    // Accepts the raw typed-envelope bytes the trainer submitted (dense,
+    // sparse top-k, or int8-quantized — see `SchemaUtils::decode_update`) and
+    // the model `version` they were computed against. Updates staler than
+    // `MAX_STALENESS` are rejected here; the version of accepted ones is
+    // stored so `update_weights` can dampen them by staleness.
+    // NOTE: this crate snapshot has no transaction/contract module calling
+    // `check_pending`; whatever does needs to pass the trainer's raw envelope
+    // bytes and base version alongside it.
+    pub fn check_pending(&mut self, trainer_addr: &Address, update_bytes: &Vec<u8>, version: u32) -> bool{
         if self.pending_transactions.contains(trainer_addr) {
             return false;
         }
         else {
-            self.pending_transactions.put(&trainer_addr, 
-                SchemaUtils::float_vec_to_byte_slice(&updates));
-            
+            // Decoding validates the envelope's tag, length, and finiteness
+            // before anything from an untrusted trainer is persisted; a
+            // NaN-laden payload let through would reach the aggregated model,
+            // since the Krum/trimmed-mean `partial_cmp` sorts in
+            // `update_weights` treat NaN comparisons as equal.
+            if let Err(err) = SchemaUtils::decode_update(update_bytes) {
+                if DEBUG {
+                    println!("Rejecting update from {:?}: {:?}", trainer_addr, err);
+                }
+                return false;
+            }
+
+            let latest_version = self
+                .public
+                .latest_version_addr
+                .get()
+                .and_then(|addr| self.public.models.get(&addr))
+                .map(|model| model.version)
+                .unwrap_or(0);
+            // A trainer cannot have legitimately trained against a model
+            // version that doesn't exist yet; `saturating_sub` would otherwise
+            // let a forged future `version` read back as zero staleness.
+            if version > latest_version {
+                if DEBUG {
+                    println!(
+                        "Rejecting update from {:?}: claimed future model version {} > latest {}",
+                        trainer_addr, version, latest_version
+                    );
+                }
+                return false;
+            }
+            let staleness = latest_version - version;
+            if staleness > MAX_STALENESS {
+                if DEBUG {
+                    println!(
+                        "Rejecting stale update from {:?}: staleness {} > MAX_STALENESS {}",
+                        trainer_addr, staleness, MAX_STALENESS
+                    );
+                }
+                return false;
+            }
+
+            self.pending_transactions.put(&trainer_addr, update_bytes.clone());
+            self.pending_versions.put(&trainer_addr, version);
+
             // Check ratio of contributors
-            let mut ratio = 0.0; 
+            let mut ratio = 0.0;
             for contributor_addr in self.pending_transactions.keys(){
-                ratio += self.trainers_scores.get(&contributor_addr).unwrap()
+                let score = self.trainers_scores.get(&contributor_addr).unwrap()
                     .parse::<f32>().unwrap();
+                if score <= SCORE_FLOOR {
+                    // Slashed trainers don't count toward the majority ratio.
+                    continue;
+                }
+                ratio += score;
             }
             if ratio >= MAJORITY_RATIO {
                 return true;
@@ -177,15 +442,189 @@ impl SchemaUtils {
         return PublicKey::new(byte_array);
     }
 
-    pub fn float_vec_to_byte_slice<'a>(floats: &Vec<f32>) -> Vec<u8> {
-        unsafe {
-            std::slice::from_raw_parts(floats.as_ptr() as *const _, (MODEL_SIZE * 4) as usize).to_vec()
+    /// Encodes `floats` as a tag-0 (dense f32) envelope: one tag byte followed
+    /// by `MODEL_SIZE` little-endian f32s.
+    pub fn encode_dense(floats: &[f32]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(1 + floats.len() * 4);
+        bytes.push(TAG_DENSE);
+        for f in floats {
+            bytes.extend_from_slice(&f.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Encodes a top-k sparse update as a tag-1 envelope: one tag byte
+    /// followed by `(u32 index, f32 value)` pairs for the non-zero entries.
+    pub fn encode_sparse(entries: &[(u32, f32)]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(1 + entries.len() * 8);
+        bytes.push(TAG_SPARSE_TOPK);
+        for (index, value) in entries {
+            bytes.extend_from_slice(&index.to_le_bytes());
+            bytes.extend_from_slice(&value.to_le_bytes());
         }
+        bytes
+    }
+
+    /// Encodes `values` as a tag-2 int8-quantized envelope: one tag byte, a
+    /// per-tensor f32 scale, then one quantized byte per coordinate.
+    pub fn encode_quantized(values: &[f32], scale: f32) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(5 + values.len());
+        bytes.push(TAG_QUANTIZED_INT8);
+        bytes.extend_from_slice(&scale.to_le_bytes());
+        bytes.extend(
+            values
+                .iter()
+                .map(|&v| (v / scale).round().max(-128.0).min(127.0) as i8 as u8),
+        );
+        bytes
     }
-    
-    pub fn byte_slice_to_float_vec<'a>(bytes: &Vec<u8>) -> Vec<f32> {
-        unsafe {
-            std::slice::from_raw_parts(bytes.as_ptr() as *const f32, MODEL_SIZE as usize).to_vec()
+
+    /// Decodes a typed update envelope submitted by an (untrusted) trainer,
+    /// dispatching on its leading tag byte and densifying the result into a
+    /// full `Vec<f32>` of length `MODEL_SIZE`. Never panics on malformed
+    /// input; the caller should reject the transaction on `Err`.
+    pub fn decode_update(bytes: &Vec<u8>) -> Result<Vec<f32>, DecodeError> {
+        let (tag, payload) = bytes.split_first().ok_or(DecodeError::Empty)?;
+        let dense = match *tag {
+            TAG_DENSE => Self::decode_dense(payload)?,
+            TAG_SPARSE_TOPK => Self::decode_sparse(payload)?,
+            TAG_QUANTIZED_INT8 => Self::decode_quantized(payload)?,
+            other => return Err(DecodeError::UnknownTag(other)),
+        };
+        if dense.iter().any(|v| !v.is_finite()) {
+            return Err(DecodeError::NonFiniteValue);
+        }
+        Ok(dense)
+    }
+
+    fn decode_dense(payload: &[u8]) -> Result<Vec<f32>, DecodeError> {
+        let expected = MODEL_SIZE as usize * 4;
+        if payload.len() != expected {
+            return Err(DecodeError::WrongLength { expected, actual: payload.len() });
+        }
+        Ok(payload
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect())
+    }
+
+    fn decode_sparse(payload: &[u8]) -> Result<Vec<f32>, DecodeError> {
+        if payload.len() % 8 != 0 {
+            return Err(DecodeError::WrongLength {
+                expected: (payload.len() / 8) * 8,
+                actual: payload.len(),
+            });
+        }
+        let mut dense = vec![0.0f32; MODEL_SIZE as usize];
+        for pair in payload.chunks_exact(8) {
+            let index = u32::from_le_bytes(pair[0..4].try_into().unwrap());
+            let value = f32::from_le_bytes(pair[4..8].try_into().unwrap());
+            if index as usize >= dense.len() {
+                return Err(DecodeError::IndexOutOfBounds(index));
+            }
+            dense[index as usize] = value;
         }
+        Ok(dense)
+    }
+
+    fn decode_quantized(payload: &[u8]) -> Result<Vec<f32>, DecodeError> {
+        if payload.len() < 4 {
+            return Err(DecodeError::WrongLength {
+                expected: 4 + MODEL_SIZE as usize,
+                actual: payload.len(),
+            });
+        }
+        let scale = f32::from_le_bytes(payload[0..4].try_into().unwrap());
+        let values = &payload[4..];
+        if values.len() != MODEL_SIZE as usize {
+            return Err(DecodeError::WrongLength {
+                expected: MODEL_SIZE as usize,
+                actual: values.len(),
+            });
+        }
+        Ok(values.iter().map(|&b| (b as i8) as f32 * scale).collect())
+    }
+
+    /// Cosine similarity between two vectors; 0 if either is the zero vector.
+    pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+        let dot: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+        let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+        let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm_a == 0.0 || norm_b == 0.0 {
+            0.0
+        } else {
+            dot / (norm_a * norm_b)
+        }
+    }
+
+    /// Squared Euclidean distance between two update vectors.
+    fn squared_distance(a: &[f32], b: &[f32]) -> f64 {
+        a.iter()
+            .zip(b.iter())
+            .map(|(x, y)| {
+                let d = (*x - *y) as f64;
+                d * d
+            })
+            .sum()
+    }
+
+    /// Computes each update's Krum score: the sum of squared distances to its
+    /// `n - f - 2` closest other updates.
+    fn krum_scores(updates: &[Vec<f32>], f: usize) -> Vec<f64> {
+        let n = updates.len();
+        let neighbors = n.saturating_sub(f + 2);
+        updates
+            .iter()
+            .enumerate()
+            .map(|(i, u)| {
+                let mut distances: Vec<f64> = updates
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .map(|(_, other)| Self::squared_distance(u, other))
+                    .collect();
+                distances.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+                distances.iter().take(neighbors).sum()
+            })
+            .collect()
+    }
+
+    /// Selects the `n - f` updates with the smallest Krum scores, returning
+    /// each paired with its reputation weight renormalized to sum to 1.
+    pub fn multi_krum_select(
+        updates: &[(Address, Vec<f32>, f32)],
+        f: usize,
+    ) -> Vec<(Vec<f32>, f32)> {
+        let n = updates.len();
+        let m = n.saturating_sub(f);
+        let vectors: Vec<Vec<f32>> = updates.iter().map(|(_, v, _)| v.clone()).collect();
+        let scores = Self::krum_scores(&vectors, f);
+
+        let mut ranked: Vec<usize> = (0..n).collect();
+        ranked.sort_by(|&a, &b| scores[a].partial_cmp(&scores[b]).unwrap_or(std::cmp::Ordering::Equal));
+        ranked.truncate(m);
+
+        let weight_sum: f32 = ranked.iter().map(|&i| updates[i].2).sum();
+        ranked
+            .into_iter()
+            .map(|i| (updates[i].1.clone(), updates[i].2 / weight_sum))
+            .collect()
+    }
+
+    /// Coordinate-wise trimmed mean: for each of the `MODEL_SIZE` coordinates,
+    /// drops the highest/lowest `beta * n` values across trainers before averaging.
+    pub fn trimmed_mean(updates: &[(Address, Vec<f32>, f32)], beta: f32) -> Vec<f32> {
+        let n = updates.len();
+        let trim = ((beta * n as f32).round() as usize).min(n.saturating_sub(1) / 2);
+        let size = updates.first().map(|(_, v, _)| v.len()).unwrap_or(0);
+
+        (0..size)
+            .map(|coord| {
+                let mut values: Vec<f32> = updates.iter().map(|(_, v, _)| v[coord]).collect();
+                values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+                let kept = &values[trim..values.len() - trim];
+                kept.iter().sum::<f32>() / kept.len() as f32
+            })
+            .collect()
     }
 }